@@ -2,8 +2,10 @@ use std::net::{TcpStream, SocketAddr, ToSocketAddrs};
 use std::env;
 use std::process;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::thread;
+use std::sync::{mpsc, Mutex};
+use std::io::{Read, Write};
 
 struct ResultCollection {
     iterations: u32,
@@ -12,13 +14,52 @@ struct ResultCollection {
     millis_max: f64,
     millis_squared: f64,
     millis_added: f64,
+    bandwidth_successes: u32,
+    mbps_min: f64,
+    mbps_max: f64,
+    mbps_added: f64,
 }
 
 impl ResultCollection {
     fn new() -> ResultCollection {
         let millis_min = std::f64::MAX;
         let millis_max = std::f64::MIN;
-        ResultCollection { iterations: 0, successes: 0, millis_min, millis_max, millis_squared: 0.0, millis_added: 0.0 }
+        let mbps_min = std::f64::MAX;
+        let mbps_max = std::f64::MIN;
+        ResultCollection { iterations: 0, successes: 0, millis_min, millis_max, millis_squared: 0.0, millis_added: 0.0,
+                            bandwidth_successes: 0, mbps_min, mbps_max, mbps_added: 0.0 }
+    }
+
+    fn add_bandwidth_interval(&mut self, mbps: f64) {
+        self.bandwidth_successes = self.bandwidth_successes + 1;
+        self.mbps_added = self.mbps_added + mbps;
+        if mbps < self.mbps_min {
+            self.mbps_min = mbps;
+        }
+        if mbps > self.mbps_max {
+            self.mbps_max = mbps;
+        }
+    }
+
+    fn get_mbps_avg(&self) -> f64 {
+        match self.bandwidth_successes {
+            0 => 0.0,
+            _ => self.mbps_added / (self.bandwidth_successes as f64),
+        }
+    }
+
+    fn get_mbps_min(&self) -> f64 {
+        match self.bandwidth_successes {
+            0 => 0.0,
+            _ => self.mbps_min,
+        }
+    }
+
+    fn get_mbps_max(&self) -> f64 {
+        match self.bandwidth_successes {
+            0 => 0.0,
+            _ => self.mbps_max,
+        }
     }
 
     fn add_interval(&mut self, successful: bool, millis: f64) {
@@ -66,15 +107,41 @@ impl ResultCollection {
             _ => self.millis_max,
         }
     }
+
+    fn get_loss_pct(&self) -> f64 {
+        match self.iterations {
+            0 => 0.0,
+            _ => 100.0 * (1.0 - (self.successes as f64 / self.iterations as f64)),
+        }
+    }
+}
+
+/// A single resolved probe target. `ProgParameters` holds one of these per
+/// `-h`/`-p` pair (or comma-separated list entry) so `run_connection_tests`
+/// can fan out across all of them concurrently.
+struct Target {
+    host: String,
+    port: String,
+    socket_addrs: Vec<SocketAddr>,
 }
 
 struct ProgParameters {
-    target_host: String,
-    target_port: String,
+    targets: Vec<Target>,
     interval_count: u32,
     connection_timeout: std::time::Duration,
     wait_interval: std::time::Duration,
-    bare_socket: SocketAddr,
+    connect_attempt_delay: std::time::Duration,
+    json_output: bool,
+    probe_payload: Option<String>,
+    read_response: bool,
+    bandwidth_bytes: Option<u64>,
+    bandwidth_direction: BandwidthDirection,
+}
+
+#[derive(Clone, Copy)]
+enum BandwidthDirection {
+    Upload,
+    Download,
 }
 
 #[derive(Hash, Eq, PartialEq)]
@@ -85,6 +152,9 @@ enum CmdLineOpts {
     Intervals,
     TimeOut,
     Wait,
+    Delay,
+    Payload,
+    Bandwidth,
     Unset,
 }
 
@@ -93,6 +163,9 @@ impl ProgParameters {
         let args_iter = args.iter();
         let mut option_map = HashMap::new();
         let mut cmd_line_opt = CmdLineOpts::AppName;
+        let mut json_output = false;
+        let mut read_response = false;
+        let mut bandwidth_direction = BandwidthDirection::Upload;
 
         for arg in args_iter {
             match cmd_line_opt {
@@ -106,6 +179,13 @@ impl ProgParameters {
                         "-i" => CmdLineOpts::Intervals,
                         "-t" => CmdLineOpts::TimeOut,
                         "-w" => CmdLineOpts::Wait,
+                        "-d" => CmdLineOpts::Delay,
+                        "-S" => CmdLineOpts::Payload,
+                        "-B" => CmdLineOpts::Bandwidth,
+                        //flag-only options, take no value
+                        "-j" => { json_output = true; CmdLineOpts::Unset },
+                        "-R" => { read_response = true; CmdLineOpts::Unset },
+                        "-D" => { bandwidth_direction = BandwidthDirection::Download; CmdLineOpts::Unset },
                         _ => return Err("Invalid Parameters"),
                     }
                 },
@@ -154,72 +234,605 @@ impl ProgParameters {
             None => std::time::Duration::from_secs(5),
         };
 
-        let target_host = match option_map.get(&CmdLineOpts::HostName) {
-            Some(val) => val.to_string(),
-            None => return Err("Host required!"),
+        let connect_attempt_delay = match option_map.get(&CmdLineOpts::Delay) {
+            Some(val) => {
+                match val.parse::<u64>() {
+                    Ok(delay) => std::time::Duration::from_millis(delay),
+                    Err(_) => return Err("Invalid Delay"),
+                }
+            }
+            None => std::time::Duration::from_millis(250),
         };
 
-        let target_port = match option_map.get(&CmdLineOpts::PortVal) {
-            Some(val) => val.to_string(),
-            None => return Err("Port required!"),
+        let probe_payload = option_map.get(&CmdLineOpts::Payload).map(|val| unescape_payload(val));
+
+        let bandwidth_bytes = match option_map.get(&CmdLineOpts::Bandwidth) {
+            Some(val) => {
+                match val.parse::<u64>() {
+                    Ok(bytes) if bytes > 0 => Some(bytes),
+                    Ok(_) => return Err("Need at least 1 byte for -B"),
+                    Err(_) => return Err("Invalid Bandwidth byte count"),
+                }
+            }
+            None => None,
         };
 
-        let mut socket_iter = match format!("{}:{}",target_host, target_port).to_socket_addrs() {
-            Ok(iter) => iter,
-            Err(_) => return Err("Invalid host/port"),
+        let host_list: Vec<&str> = match option_map.get(&CmdLineOpts::HostName) {
+            Some(val) => val.split(',').collect(),
+            None => return Err("Host required!"),
         };
 
-        let bare_socket = match socket_iter.next() {
-            Some(socket) => socket,
-            None => return Err("Unresolvable host/port"),
+        let port_list: Vec<&str> = match option_map.get(&CmdLineOpts::PortVal) {
+            Some(val) => val.split(',').collect(),
+            None => return Err("Port required!"),
         };
 
-        Ok(ProgParameters {interval_count, wait_interval, connection_timeout, target_host, target_port, bare_socket})
+        //a single port applies to every host; otherwise hosts and ports are paired up
+        if port_list.len() != 1 && port_list.len() != host_list.len() {
+            return Err("Host/Port count mismatch");
+        }
+
+        let mut targets = Vec::with_capacity(host_list.len());
+        for (index, host) in host_list.iter().enumerate() {
+            let port = if port_list.len() == 1 { port_list[0] } else { port_list[index] };
+
+            let socket_iter = match format!("{}:{}", host, port).to_socket_addrs() {
+                Ok(iter) => iter,
+                Err(_) => return Err("Invalid host/port"),
+            };
+
+            let resolved: Vec<SocketAddr> = socket_iter.collect();
+            if resolved.is_empty() {
+                return Err("Unresolvable host/port");
+            }
+
+            targets.push(Target { host: host.to_string(), port: port.to_string(), socket_addrs: interleave_by_family(resolved) });
+        }
+
+        Ok(ProgParameters {interval_count, wait_interval, connection_timeout, connect_attempt_delay, targets, json_output, probe_payload, read_response, bandwidth_bytes, bandwidth_direction})
     }
 
     fn get_usage() -> &'static str {
-        return "tcping -h -p -i -t -w\n\n \
-        \t-h\t(required) Host name, ipv4, or ipv6 address\n \
-        \t-p\t(required) Port (1-65535)\n \
+        return "tcping -h -p -i -t -w -d -j -S -R -B -D\n\n \
+        \t-h\t(required) Host name, ipv4, or ipv6 address. Comma-separate for multiple targets\n \
+        \t-p\t(required) Port (1-65535). One value applies to all hosts, or comma-separate to pair with -h\n \
         \t-i\t(required) Intervals.  Number of tests to run before exit\n \
         \t-t\tConnection Timeout. Wait before failing connection attempt (Default: OS Defined)\n \
-        \t-w\tWait Interval. Wait between intervals in seconds (Default: 1)\n"
+        \t-w\tWait Interval. Wait between intervals in seconds (Default: 1)\n \
+        \t-d\tConnection Attempt Delay. Happy Eyeballs delay in ms before racing the next address (Default: 250)\n \
+        \t-j\tJSON output. Emit newline-delimited JSON instead of human-readable text\n \
+        \t-S\tApplication payload to write after connect (e.g. \"HEAD / HTTP/1.0\\r\\n\\r\\n\"). Implies -R\n \
+        \t-R\tRead the first response bytes after connect and report time-to-first-byte\n \
+        \t-B\tBandwidth mode. After connect, transfer this many bytes and report throughput in Mbps\n \
+        \t-D\tBandwidth direction is download (read bytes from peer) instead of the default upload (write bytes to peer)\n"
+    }
+}
+
+/// Expands the common backslash escapes a shell won't expand for us, so
+/// users can pass `-S "HEAD / HTTP/1.0\r\n\r\n"` literally.
+fn unescape_payload(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('r') => result.push('\r'),
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('\\') => result.push('\\'),
+                Some(other) => { result.push('\\'); result.push(other); },
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Reorders resolved addresses per RFC 8305: alternate address families,
+/// starting with IPv6, so a slow/unreachable family can't starve the other.
+fn interleave_by_family(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<SocketAddr>, Vec<SocketAddr>) = (Vec::new(), Vec::new());
+    for addr in addrs {
+        if addr.is_ipv6() {
+            v6.push(addr);
+        } else {
+            v4.push(addr);
+        }
+    }
+
+    let mut interleaved = Vec::with_capacity(v6.len() + v4.len());
+    let mut v6_iter = v6.into_iter();
+    let mut v4_iter = v4.into_iter();
+    loop {
+        let next_v6 = v6_iter.next();
+        let next_v4 = v4_iter.next();
+        match (next_v6, next_v4) {
+            (None, None) => break,
+            (a, b) => {
+                if let Some(addr) = a { interleaved.push(addr); }
+                if let Some(addr) = b { interleaved.push(addr); }
+            }
+        }
+    }
+    interleaved
+}
+
+enum ConnectOutcome {
+    Connected(SocketAddr, TcpStream, Duration),
+    Failed(std::io::Error),
+}
+
+/// RFC 8305 Happy Eyeballs: races non-blocking connects across `addrs`,
+/// staggering each new attempt by `attempt_delay` so earlier attempts keep
+/// racing rather than being abandoned. Returns the first socket to connect
+/// along with the address it reached and how long that attempt took.
+/// `overall_timeout` is a hard cap across every attempt combined.
+///
+/// Addresses that are still racing once a winner is found (or the caller's
+/// round gives up) are not cancelled — this function returns without
+/// joining them, and they're left to exit on their own once their own
+/// `connect_timeout` call elapses, bounded by what was left of
+/// `overall_timeout` when they started. For a single call that's a bounded
+/// amount of cleanup work; for a long-running multi-target monitor calling
+/// this once per target per `-w` interval, it means several rounds' worth
+/// of loser threads can be alive concurrently per target if the wait
+/// interval is shorter than `overall_timeout`. That's a real resource-growth
+/// tradeoff against the alternative (blocking the winning path on stragglers
+/// before returning), not yet addressed by bounding or joining them here.
+fn happy_eyeballs_connect(addrs: &[SocketAddr], attempt_delay: Duration, overall_timeout: Duration) -> Result<(SocketAddr, TcpStream, Duration), std::io::Error> {
+    let (tx, rx) = mpsc::channel::<ConnectOutcome>();
+    let overall_start = Instant::now();
+
+    for (index, addr) in addrs.iter().enumerate() {
+        let addr = *addr;
+        let tx = tx.clone();
+        thread::spawn(move || {
+            thread::sleep(attempt_delay * index as u32);
+            //bound this attempt by what's left of the overall budget, not the full
+            //budget, so a late-staggered attempt can't run past the overall deadline of the call that spawned it
+            let remaining = overall_timeout.checked_sub(overall_start.elapsed()).unwrap_or(Duration::from_millis(0));
+            if remaining.is_zero() {
+                let _ = tx.send(ConnectOutcome::Failed(std::io::Error::new(std::io::ErrorKind::TimedOut, "connection timed out")));
+                return;
+            }
+            let attempt_start = Instant::now();
+            match TcpStream::connect_timeout(&addr, remaining) {
+                Ok(stream) => { let _ = tx.send(ConnectOutcome::Connected(addr, stream, attempt_start.elapsed())); },
+                Err(error) => { let _ = tx.send(ConnectOutcome::Failed(error)); },
+            }
+        });
+    }
+    drop(tx);
+
+    let mut last_error = None;
+    let mut failures = 0;
+    while failures < addrs.len() {
+        let remaining = overall_timeout.checked_sub(overall_start.elapsed()).unwrap_or(Duration::from_millis(0));
+        match rx.recv_timeout(remaining) {
+            Ok(ConnectOutcome::Connected(addr, stream, elapsed)) => return Ok((addr, stream, elapsed)),
+            Ok(ConnectOutcome::Failed(error)) => {
+                failures = failures + 1;
+                last_error = Some(error);
+            },
+            Err(_) => break,
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::TimedOut, "connection timed out")))
+}
+
+/// One probed interval, independent of how it will be printed.
+struct ProbeRecord {
+    host: String,
+    port: String,
+    sequence: u32,
+    addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    success: bool,
+    millis: f64,
+    error: Option<String>,
+    ttfb_millis: Option<f64>,
+    probe_error: Option<String>,
+    mbps: Option<f64>,
+    bandwidth_error: Option<String>,
+}
+
+/// A target's final throughput stats, present only when `-B` is in effect.
+struct BandwidthSummary {
+    min_mbps: f64,
+    max_mbps: f64,
+    avg_mbps: f64,
+}
+
+/// A target's final stats, ready for a comparative summary across targets.
+/// `worker_error` is set instead of the other fields when the target's
+/// worker thread panicked before it could finish collecting results; see
+/// `TargetSummary::errored`.
+struct TargetSummary {
+    host: String,
+    port: String,
+    successes: u32,
+    iterations: u32,
+    loss_pct: f64,
+    min: f64,
+    max: f64,
+    avg: f64,
+    std_dev: f64,
+    bandwidth: Option<BandwidthSummary>,
+    worker_error: Option<String>,
+}
+
+impl TargetSummary {
+    fn new(target: &Target, prog_params: &ProgParameters, result_col: &ResultCollection) -> TargetSummary {
+        let bandwidth = if prog_params.bandwidth_bytes.is_some() {
+            Some(BandwidthSummary { min_mbps: result_col.get_mbps_min(), max_mbps: result_col.get_mbps_max(), avg_mbps: result_col.get_mbps_avg() })
+        } else {
+            None
+        };
+        TargetSummary {
+            host: target.host.clone(),
+            port: target.port.clone(),
+            successes: result_col.successes,
+            iterations: result_col.iterations,
+            loss_pct: result_col.get_loss_pct(),
+            min: result_col.get_min(),
+            max: result_col.get_max(),
+            avg: result_col.get_avg(),
+            std_dev: result_col.get_std_dev(),
+            bandwidth,
+            worker_error: None,
+        }
+    }
+
+    /// A placeholder summary for a target whose worker thread panicked, so
+    /// one bad target can't erase the other targets' already-collected
+    /// results out of the comparative summary.
+    fn errored(target: &Target, reason: &str) -> TargetSummary {
+        TargetSummary {
+            host: target.host.clone(),
+            port: target.port.clone(),
+            successes: 0,
+            iterations: 0,
+            loss_pct: 0.0,
+            min: 0.0,
+            max: 0.0,
+            avg: 0.0,
+            std_dev: 0.0,
+            bandwidth: None,
+            worker_error: Some(reason.to_string()),
+        }
+    }
+}
+
+/// Writes `payload` (if any) to `stream` and reads the first response bytes,
+/// returning the elapsed time-to-first-byte. Used for the optional
+/// application-layer liveness check after a bare TCP connect succeeds.
+/// `io_timeout` bounds the write and the read so a peer that connects but
+/// never speaks can't hang the probe past `-t`.
+fn app_layer_probe(stream: &mut TcpStream, payload: Option<&str>, io_timeout: Duration) -> std::io::Result<Duration> {
+    stream.set_write_timeout(Some(io_timeout))?;
+    stream.set_read_timeout(Some(io_timeout))?;
+    if let Some(payload) = payload {
+        stream.write_all(payload.as_bytes())?;
+    }
+    let start = Instant::now();
+    let mut buf = [0u8; 512];
+    let bytes_read = stream.read(&mut buf)?;
+    if bytes_read == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "peer closed without sending any bytes"));
+    }
+    Ok(start.elapsed())
+}
+
+const BANDWIDTH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Transfers exactly `byte_count` bytes over `stream` (uploading to or
+/// draining from the peer, per `direction`) and returns the achieved
+/// throughput in Mbps, or an error if the peer closes or stalls before the
+/// full count is reached. `io_timeout` bounds each individual read/write so
+/// a peer that stops sending/accepting mid-transfer can't hang the probe.
+fn bandwidth_probe(stream: &mut TcpStream, byte_count: u64, direction: BandwidthDirection, io_timeout: Duration) -> std::io::Result<f64> {
+    stream.set_write_timeout(Some(io_timeout))?;
+    stream.set_read_timeout(Some(io_timeout))?;
+    let chunk = vec![0u8; BANDWIDTH_CHUNK_SIZE];
+    let mut remaining = byte_count;
+    let start = Instant::now();
+
+    match direction {
+        BandwidthDirection::Upload => {
+            while remaining > 0 {
+                let to_write = std::cmp::min(remaining, chunk.len() as u64) as usize;
+                stream.write_all(&chunk[..to_write])?;
+                remaining -= to_write as u64;
+            }
+        },
+        BandwidthDirection::Download => {
+            let mut buf = vec![0u8; BANDWIDTH_CHUNK_SIZE];
+            while remaining > 0 {
+                let to_read = std::cmp::min(remaining, buf.len() as u64) as usize;
+                let read = stream.read(&mut buf[..to_read])?;
+                if read == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "peer closed before byte count was reached"));
+                }
+                remaining -= read as u64;
+            }
+        },
+    }
+
+    let elapsed_secs = start.elapsed().as_secs_f64();
+    let mbps = if elapsed_secs > 0.0 { (byte_count as f64 * 8.0) / (elapsed_secs * 1_000_000.0) } else { 0.0 };
+    Ok(mbps)
+}
+
+/// Formats probe and summary records; `run_connection_tests` is agnostic to
+/// which formatting is in effect. Shared across worker threads behind a
+/// `Mutex` so concurrent targets don't interleave partial lines.
+trait OutputWriter: Send {
+    fn write_probe(&mut self, probe: &ProbeRecord);
+    fn write_summary(&mut self, summaries: &[TargetSummary]);
+}
+
+struct TextOutputWriter;
+
+impl OutputWriter for TextOutputWriter {
+    fn write_probe(&mut self, probe: &ProbeRecord) {
+        if probe.success {
+            let addr = probe.addr.unwrap();
+            let family = if addr.is_ipv6() { "IPv6" } else { "IPv4" };
+            println!("Connected {}:{} ({}, {}) - {:.3}ms", probe.host, probe.port, family, addr, probe.millis);
+            if let Some(local_addr) = probe.local_addr {
+                println!("  {} -> {}", local_addr, addr);
+            }
+            match (probe.ttfb_millis, &probe.probe_error) {
+                (Some(ttfb), _) => println!("  first byte in {:.3}ms", ttfb),
+                (None, Some(probe_error)) => println!("  app probe failed: {}", probe_error),
+                (None, None) => {},
+            }
+            match (probe.mbps, &probe.bandwidth_error) {
+                (Some(mbps), _) => println!("  throughput {:.3} Mbps", mbps),
+                (None, Some(bandwidth_error)) => println!("  bandwidth probe failed: {}", bandwidth_error),
+                (None, None) => {},
+            }
+        } else {
+            println!("Failed {}:{} - {}", probe.host, probe.port, probe.error.as_deref().unwrap_or("unknown error"));
+        }
+    }
+
+    fn write_summary(&mut self, summaries: &[TargetSummary]) {
+        let show_bandwidth = summaries.iter().any(|summary| summary.bandwidth.is_some());
+        if show_bandwidth {
+            println!("\n{:<24} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}", "TARGET", "SUCCESS", "ATTEMPTS", "MIN", "MAX", "AVG", "DEV", "MIN_MBPS", "MAX_MBPS", "AVG_MBPS");
+        } else {
+            println!("\n{:<24} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}", "TARGET", "SUCCESS", "ATTEMPTS", "MIN", "MAX", "AVG", "DEV");
+        }
+        for summary in summaries {
+            if let Some(worker_error) = &summary.worker_error {
+                println!("{:<24} worker thread panicked: {}", format!("{}:{}", summary.host, summary.port), worker_error);
+            } else if let Some(bandwidth) = &summary.bandwidth {
+                println!("{:<24} {:>9} {:>9} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                          format!("{}:{}", summary.host, summary.port),
+                          summary.successes,
+                          summary.iterations,
+                          summary.min,
+                          summary.max,
+                          summary.avg,
+                          summary.std_dev,
+                          bandwidth.min_mbps,
+                          bandwidth.max_mbps,
+                          bandwidth.avg_mbps);
+            } else {
+                println!("{:<24} {:>9} {:>9} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                          format!("{}:{}", summary.host, summary.port),
+                          summary.successes,
+                          summary.iterations,
+                          summary.min,
+                          summary.max,
+                          summary.avg,
+                          summary.std_dev);
+            }
+        }
+    }
+}
+
+struct JsonOutputWriter;
+
+impl JsonOutputWriter {
+    /// Escapes `value` for embedding in a JSON string literal. Host/port come
+    /// straight from user-supplied `-h`/`-p` args and error strings can carry
+    /// arbitrary OS text, so this covers the full JSON control-character set
+    /// (not just the two cases needed to avoid breaking the string delimiter)
+    /// to keep NDJSON output parseable.
+    fn escape(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '\u{08}' => escaped.push_str("\\b"),
+                '\u{0C}' => escaped.push_str("\\f"),
+                c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped
     }
 }
 
-fn run_connection_tests(result_col: &mut ResultCollection, prog_params: &ProgParameters) {
+impl OutputWriter for JsonOutputWriter {
+    fn write_probe(&mut self, probe: &ProbeRecord) {
+        let addr_field = match probe.addr {
+            Some(addr) => format!("\"{}\"", addr),
+            None => "null".to_string(),
+        };
+        let local_addr_field = match probe.local_addr {
+            Some(local_addr) => format!("\"{}\"", local_addr),
+            None => "null".to_string(),
+        };
+        let rtt_field = if probe.success { format!("{:.3}", probe.millis) } else { "null".to_string() };
+        let ttfb_field = match probe.ttfb_millis {
+            Some(ttfb) => format!("{:.3}", ttfb),
+            None => "null".to_string(),
+        };
+        let error_field = match &probe.error {
+            Some(error) => format!("\"{}\"", JsonOutputWriter::escape(error)),
+            None => "null".to_string(),
+        };
+        let probe_error_field = match &probe.probe_error {
+            Some(probe_error) => format!("\"{}\"", JsonOutputWriter::escape(probe_error)),
+            None => "null".to_string(),
+        };
+        let mbps_field = match probe.mbps {
+            Some(mbps) => format!("{:.3}", mbps),
+            None => "null".to_string(),
+        };
+        let bandwidth_error_field = match &probe.bandwidth_error {
+            Some(bandwidth_error) => format!("\"{}\"", JsonOutputWriter::escape(bandwidth_error)),
+            None => "null".to_string(),
+        };
+        println!("{{\"seq\":{},\"host\":\"{}\",\"port\":\"{}\",\"addr\":{},\"local_addr\":{},\"success\":{},\"rtt_ms\":{},\"ttfb_ms\":{},\"error\":{},\"probe_error\":{},\"mbps\":{},\"bandwidth_error\":{}}}",
+                  probe.sequence,
+                  JsonOutputWriter::escape(&probe.host),
+                  JsonOutputWriter::escape(&probe.port),
+                  addr_field,
+                  local_addr_field,
+                  probe.success,
+                  rtt_field,
+                  ttfb_field,
+                  error_field,
+                  probe_error_field,
+                  mbps_field,
+                  bandwidth_error_field);
+    }
+
+    fn write_summary(&mut self, summaries: &[TargetSummary]) {
+        for summary in summaries {
+            if let Some(worker_error) = &summary.worker_error {
+                println!("{{\"host\":\"{}\",\"port\":\"{}\",\"worker_error\":\"{}\"}}",
+                          JsonOutputWriter::escape(&summary.host),
+                          JsonOutputWriter::escape(&summary.port),
+                          JsonOutputWriter::escape(worker_error));
+                continue;
+            }
+            let bandwidth_field = match &summary.bandwidth {
+                Some(bandwidth) => format!("{{\"min_mbps\":{:.3},\"max_mbps\":{:.3},\"avg_mbps\":{:.3}}}", bandwidth.min_mbps, bandwidth.max_mbps, bandwidth.avg_mbps),
+                None => "null".to_string(),
+            };
+            println!("{{\"host\":\"{}\",\"port\":\"{}\",\"successes\":{},\"attempts\":{},\"loss_pct\":{:.3},\"min_ms\":{:.3},\"max_ms\":{:.3},\"avg_ms\":{:.3},\"stddev_ms\":{:.3},\"bandwidth\":{}}}",
+                      JsonOutputWriter::escape(&summary.host),
+                      JsonOutputWriter::escape(&summary.port),
+                      summary.successes,
+                      summary.iterations,
+                      summary.loss_pct,
+                      summary.min,
+                      summary.max,
+                      summary.avg,
+                      summary.std_dev,
+                      bandwidth_field);
+        }
+    }
+}
+
+/// Probes a single target for `prog_params.interval_count` rounds. Each round
+/// is scheduled against `schedule_start + round * wait_interval` rather than
+/// synchronized with the other targets' threads, so a target that's stuck in
+/// a slow or hung connect/probe only delays itself — it can never block the
+/// other targets from keeping their own cadence.
+fn run_target_probes(target: &Target, prog_params: &ProgParameters, writer: &Mutex<Box<dyn OutputWriter>>, schedule_start: Instant) -> ResultCollection {
+    let app_probe_enabled = prog_params.probe_payload.is_some() || prog_params.read_response;
+    let mut result_col = ResultCollection::new();
     loop {
+        let round_start = schedule_start + prog_params.wait_interval * result_col.iterations;
         let now = Instant::now();
-        match TcpStream::connect_timeout(&prog_params.bare_socket, prog_params.connection_timeout) {
-            Ok(stream) => {
-                let millis = (now.elapsed().as_micros() as f64) / 1000.0; //millis as a fraction
-                println!("Connected {}:{} - {:.3}ms", prog_params.target_host, prog_params.target_port, millis);
+        if round_start > now {
+            thread::sleep(round_start - now);
+        }
+        let sequence = result_col.iterations;
+        let probe = match happy_eyeballs_connect(&target.socket_addrs, prog_params.connect_attempt_delay, prog_params.connection_timeout) {
+            Ok((addr, mut stream, elapsed)) => {
+                let millis = (elapsed.as_micros() as f64) / 1000.0; //millis as a fraction
                 result_col.add_interval(true, millis);
-                stream.shutdown(std::net::Shutdown::Both).unwrap();
+                let local_addr = stream.local_addr().ok();
+
+                let (ttfb_millis, probe_error) = if app_probe_enabled {
+                    match app_layer_probe(&mut stream, prog_params.probe_payload.as_deref(), prog_params.connection_timeout) {
+                        Ok(ttfb) => (Some((ttfb.as_micros() as f64) / 1000.0), None),
+                        Err(error) => (None, Some(error.to_string())),
+                    }
+                } else {
+                    (None, None)
+                };
+
+                let (mbps, bandwidth_error) = match prog_params.bandwidth_bytes {
+                    Some(byte_count) => match bandwidth_probe(&mut stream, byte_count, prog_params.bandwidth_direction, prog_params.connection_timeout) {
+                        Ok(mbps) => { result_col.add_bandwidth_interval(mbps); (Some(mbps), None) },
+                        Err(error) => (None, Some(error.to_string())),
+                    },
+                    None => (None, None),
+                };
+
+                //best-effort: a peer that RSTs mid-probe (e.g. a non-keepalive HTTP
+                //server answering -S/-R) leaves the socket already disconnected, and
+                //shutdown() on Linux returns ENOTCONN in that case — not a real error
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+                ProbeRecord { host: target.host.clone(), port: target.port.clone(), sequence, addr: Some(addr), local_addr, success: true, millis, error: None, ttfb_millis, probe_error, mbps, bandwidth_error }
             },
             Err(error) => {
-                println!("Failed {}:{} - {}", prog_params.target_host, prog_params.target_port, error);
                 result_col.add_interval(false, 0.0);
+                ProbeRecord { host: target.host.clone(), port: target.port.clone(), sequence, addr: None, local_addr: None, success: false, millis: 0.0, error: Some(error.to_string()), ttfb_millis: None, probe_error: None, mbps: None, bandwidth_error: None }
             },
-        }
+        };
+        writer.lock().unwrap().write_probe(&probe);
         if result_col.iterations == prog_params.interval_count {
             break;
-        } else {
-            thread::sleep(prog_params.wait_interval);
         }
     }
+    result_col
+}
+
+/// Recovers a human-readable message from a caught panic payload, for
+/// reporting a worker thread's panic without re-raising it.
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
 
-fn display_summary(result_col: &ResultCollection, prog_params: &ProgParameters) {
-    println!("\nTCPING to {}:{}\n{} successes / {} attempts, min/max/avg/dev {:.3}/{:.3}/{:.3}/{:.3}",
-             prog_params.target_host,
-             prog_params.target_port,
-             result_col.successes,
-             result_col.iterations,
-             result_col.get_min(),
-             result_col.get_max(),
-             result_col.get_avg(),
-             result_col.get_std_dev());
+/// Fans the configured targets out across one worker thread apiece. Every
+/// thread schedules its own rounds off the same `schedule_start`, which
+/// keeps them on the same `-w` cadence without making any of them wait on
+/// each other — a stuck target can never block the rest of the fleet.
+fn run_connection_tests(prog_params: &ProgParameters, writer: Box<dyn OutputWriter>) {
+    let writer_mutex = Mutex::new(writer);
+    let schedule_start = Instant::now();
+
+    let summaries = thread::scope(|scope| {
+        let handles: Vec<_> = prog_params.targets.iter().map(|target| {
+            let writer_mutex = &writer_mutex;
+            (target, scope.spawn(move || {
+                let result_col = run_target_probes(target, prog_params, writer_mutex, schedule_start);
+                TargetSummary::new(target, prog_params, &result_col)
+            }))
+        }).collect();
+
+        // A panic in one target's worker (e.g. an I/O bug tripped by a
+        // misbehaving peer) must not take down the other targets' already-
+        // collected results — substitute a placeholder summary instead of
+        // propagating the panic out of thread::scope.
+        handles.into_iter().map(|(target, handle)| {
+            handle.join().unwrap_or_else(|panic| TargetSummary::errored(target, &panic_message(&panic)))
+        }).collect::<Vec<_>>()
+    });
+
+    writer_mutex.into_inner().unwrap().write_summary(&summaries);
 }
 
 fn main() {
@@ -228,7 +841,10 @@ fn main() {
         println!("\nERROR: {}\n\nUsage: {}", err, ProgParameters::get_usage());
         process::exit(1);
     });
-    let mut result_collection = ResultCollection::new();
-    run_connection_tests(&mut result_collection, &prog_params);
-    display_summary(&result_collection, &prog_params);
+    let writer: Box<dyn OutputWriter> = if prog_params.json_output {
+        Box::new(JsonOutputWriter)
+    } else {
+        Box::new(TextOutputWriter)
+    };
+    run_connection_tests(&prog_params, writer);
 }